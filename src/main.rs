@@ -1,7 +1,7 @@
 use clap::{Parser, Subcommand};
 
 
-use std::io::copy;
+use std::io::{copy, Read, Write};
 
 use serde::{Deserialize, Serialize};
 
@@ -18,6 +18,14 @@ use tar::{Archive, Builder, Header};
 
 use sha1::{Sha1, Digest};
 
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use xz2::read::XzDecoder;
+use xz2::write::XzEncoder;
+use bzip2::read::BzDecoder;
+use bzip2::write::BzEncoder;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -25,7 +33,11 @@ struct Args {
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
 
-    // Enable gzip
+    // Force gzip. The input archives of `diff` and the base of `apply-chain`
+    // are then decoded as gzip whatever their names. The output name must still
+    // carry a gzip extension (.tar.gz/.tgz); otherwise the output would be
+    // unreadable, since deltas and outputs are read back with the codec
+    // detected from the filename alone.
     #[arg(short = 'c', long, default_value_t = false)]
     gzip: bool,
 
@@ -42,65 +54,397 @@ enum Commands {
 
     /// Applies the diff tar file to an existing tar file
     Apply { old: PathBuf, diff: PathBuf, out: PathBuf },
+
+    /// Reconstructs a tar by folding an ordered chain of deltas onto a base
+    ApplyChain { base: PathBuf, #[arg(short, long)] out: PathBuf, deltas: Vec<PathBuf> },
+
+    /// Merges an ordered chain of deltas into one consolidated delta
+    Compact { #[arg(short, long)] out: PathBuf, deltas: Vec<PathBuf> },
+
+    /// Checks an archive against the hash manifest recorded in a delta
+    Verify { archive: PathBuf, diff: PathBuf },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The in-memory metadata describing a single delta. Always the newest shape;
+/// older on-disk variants are up-converted into this on load.
+#[derive(Debug)]
 pub struct DiffMetadata {
     changed: Vec<PathBuf>,
     added: Vec<PathBuf>,
     removed: Vec<PathBuf>,
+    codec: Codec,
+    /// Content-identical files whose POSIX header attributes changed; replayed
+    /// on apply without re-storing the file bytes.
+    metadata_changed: Vec<(PathBuf, HeaderDelta)>,
+    /// Full index of the post-apply target state, so a reconstructed archive can
+    /// be verified without the original `new` archive. `None` means no manifest
+    /// was recorded: the delta predates the manifest field (a V1-V3 or legacy
+    /// delta) or was compacted from a chain whose last delta had none. That is
+    /// distinct from `Some(empty)` — a delta whose target genuinely has zero
+    /// files.
+    manifest: Option<HashMap<PathBuf, IndexValue>>,
+}
+
+impl DiffMetadata {
+    /// Wraps the in-memory metadata in the newest on-disk version for writing.
+    fn to_versioned(&self) -> VersionedMetadata {
+        VersionedMetadata::V4(DiffMetadataV4 {
+            changed: self.changed.clone(),
+            added: self.added.clone(),
+            removed: self.removed.clone(),
+            codec: self.codec,
+            metadata_changed: self.metadata_changed.clone(),
+            manifest: self.manifest.clone(),
+        })
+    }
+}
+
+/// The original on-disk metadata: path lists only, no codec.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffMetadataV1 {
+    changed: Vec<PathBuf>,
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+}
+
+/// The second on-disk metadata: adds the compression codec.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffMetadataV2 {
+    changed: Vec<PathBuf>,
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    codec: Codec,
+}
+
+/// The third on-disk metadata: adds metadata-only header changes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffMetadataV3 {
+    changed: Vec<PathBuf>,
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    codec: Codec,
+    metadata_changed: Vec<(PathBuf, HeaderDelta)>,
+}
+
+/// The current on-disk metadata: adds the post-apply hash manifest.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiffMetadataV4 {
+    changed: Vec<PathBuf>,
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    codec: Codec,
+    metadata_changed: Vec<(PathBuf, HeaderDelta)>,
+    manifest: Option<HashMap<PathBuf, IndexValue>>,
+}
+
+/// The on-disk envelope carrying an explicit schema version, so a delta written
+/// by one release stays applyable by later ones.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "version")]
+pub enum VersionedMetadata {
+    V1(DiffMetadataV1),
+    V2(DiffMetadataV2),
+    V3(DiffMetadataV3),
+    V4(DiffMetadataV4),
+}
+
+/// The pre-versioning on-disk shape: bare path lists with no `version` tag, as
+/// written by releases before the versioned envelope existed.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LegacyMetadata {
+    changed: Vec<PathBuf>,
+    added: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+}
+
+/// What may actually be encountered on disk: either a tagged versioned envelope
+/// or a legacy untagged delta from before the version tag existed. Tried in
+/// order, so a tagged delta never matches the legacy shape.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum MetadataOnDisk {
+    Versioned(VersionedMetadata),
+    Legacy(LegacyMetadata),
+}
+
+impl MetadataOnDisk {
+    fn into_current(self) -> DiffMetadata {
+        match self {
+            MetadataOnDisk::Versioned(v) => v.into_current(),
+            MetadataOnDisk::Legacy(l) => DiffMetadata {
+                changed: l.changed,
+                added: l.added,
+                removed: l.removed,
+                codec: Codec::Plain,
+                metadata_changed: Vec::new(),
+                manifest: None,
+            },
+        }
+    }
+}
+
+impl VersionedMetadata {
+    /// Up-converts any stored version into the current in-memory metadata.
+    fn into_current(self) -> DiffMetadata {
+        match self {
+            VersionedMetadata::V1(v1) => DiffMetadata {
+                changed: v1.changed,
+                added: v1.added,
+                removed: v1.removed,
+                codec: Codec::Plain,
+                metadata_changed: Vec::new(),
+                manifest: None,
+            },
+            VersionedMetadata::V2(v2) => DiffMetadata {
+                changed: v2.changed,
+                added: v2.added,
+                removed: v2.removed,
+                codec: v2.codec,
+                metadata_changed: Vec::new(),
+                manifest: None,
+            },
+            VersionedMetadata::V3(v3) => DiffMetadata {
+                changed: v3.changed,
+                added: v3.added,
+                removed: v3.removed,
+                codec: v3.codec,
+                metadata_changed: v3.metadata_changed,
+                manifest: None,
+            },
+            VersionedMetadata::V4(v4) => DiffMetadata {
+                changed: v4.changed,
+                added: v4.added,
+                removed: v4.removed,
+                codec: v4.codec,
+                metadata_changed: v4.metadata_changed,
+                manifest: v4.manifest,
+            },
+        }
+    }
+}
+
+/// The compression codec wrapping a tar stream.
+///
+/// Auto-detected from a path's extension, but overridable with `-c/--gzip`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    Plain,
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
 }
 
-fn open_tar(path: &Path, _gzip: bool) -> Result<Archive<std::fs::File>> {
+impl Codec {
+    /// Picks the codec that matches the archive's file extension, defaulting
+    /// to a plain `.tar` when nothing recognisable is found.
+    fn from_path(path: &Path) -> Codec {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") | Some("tgz") => Codec::Gzip,
+            Some("zst") | Some("zstd") => Codec::Zstd,
+            Some("xz") => Codec::Xz,
+            Some("bz2") => Codec::Bzip2,
+            _ => Codec::Plain,
+        }
+    }
+}
+
+/// Picks the codec to compress `out` with. `-c/--gzip` forces gzip, but only if
+/// the path's extension agrees: writing a gzip stream to a name that detects as
+/// plain would produce a delta that `open_tar` later reads back undecoded, so
+/// the mismatch is refused here rather than silently emitting an unreadable
+/// archive.
+fn resolve_output_codec(out: &Path, gzip: bool) -> Result<Codec> {
+    if gzip && Codec::from_path(out) != Codec::Gzip {
+        bail!(
+            "--gzip was given but {} does not have a gzip extension; rename it to .tar.gz/.tgz or drop --gzip",
+            out.display()
+        );
+    }
+    Ok(Codec::from_path(out))
+}
+
+fn open_tar(path: &Path, gzip: bool) -> Result<Archive<Box<dyn Read>>> {
     let file = std::fs::File::open(path)?;
-    Ok(Archive::new(file))
+    let codec = if gzip { Codec::Gzip } else { Codec::from_path(path) };
+    let reader: Box<dyn Read> = match codec {
+        Codec::Plain => Box::new(file),
+        Codec::Gzip => Box::new(GzDecoder::new(file)),
+        Codec::Zstd => Box::new(zstd::Decoder::new(file)?),
+        Codec::Xz => Box::new(XzDecoder::new(file)),
+        Codec::Bzip2 => Box::new(BzDecoder::new(file)),
+    };
+    Ok(Archive::new(reader))
+}
+
+/// Creates the output file and wraps it in the encoder for `codec`, so the
+/// `tar::Builder` transparently writes a compressed archive.
+fn create_writer(path: &Path, codec: Codec) -> Result<Box<dyn Write>> {
+    let file = std::fs::File::create(path)?;
+    let writer: Box<dyn Write> = match codec {
+        Codec::Plain => Box::new(file),
+        Codec::Gzip => Box::new(GzEncoder::new(file, Compression::default())),
+        Codec::Zstd => Box::new(zstd::Encoder::new(file, 0)?.auto_finish()),
+        Codec::Xz => Box::new(XzEncoder::new(file, 6)),
+        Codec::Bzip2 => Box::new(BzEncoder::new(file, bzip2::Compression::default())),
+    };
+    Ok(writer)
+}
 
-    // if gzip {
-    //     let tar = GzDecoder::new(file);
-    //     Ok(Archive::new(tar))
-    // } else {
-    // }
+/// Normalizes an entry path to the spelling `Builder::append_data` writes, so
+/// keys used in indexes, metadata and membership sets match the bytes actually
+/// stored in the output archive. In particular a leading `./` (GNU tar's
+/// default prefix) is dropped so `"./x.txt"` and `"x.txt"` compare equal.
+fn normalize_path(path: &Path) -> PathBuf {
+    let normalized: PathBuf = path
+        .components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect();
+    if normalized.as_os_str().is_empty() {
+        // A path that was only `.`/`./` collapses to nothing; keep a single `.`.
+        PathBuf::from(".")
+    } else {
+        normalized
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IndexValue {
-    sha1: [u8; 20],
+    size: u64,
     cksum: u32,
+    /// Cheap signature: SHA-1 of only the first `PARTIAL_BLOCK` bytes.
+    partial: Option<[u8; 20]>,
+    /// Full SHA-1 of the whole entry. `create_index` leaves it unset; `diff`
+    /// fills it in for paths whose partial signature and size match, and every
+    /// manifest entry carries it.
+    sha1: Option<[u8; 20]>,
+    /// POSIX header attributes, tracked so a pure `chmod`/`chown`/touch can be
+    /// replayed without re-storing the file content.
+    mode: u32,
+    mtime: u64,
+    uid: u64,
+    gid: u64,
+}
+
+/// The new header attributes for a content-identical file, recorded so `Apply`
+/// can replay a metadata-only change onto the old entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderDelta {
+    mode: u32,
+    mtime: u64,
+    uid: u64,
+    gid: u64,
+}
+
+impl HeaderDelta {
+    fn from_index(v: &IndexValue) -> Self {
+        HeaderDelta { mode: v.mode, mtime: v.mtime, uid: v.uid, gid: v.gid }
+    }
 }
 
 const DELTA_METADATA_FILE: &str = "__delta_metadata.json";
 
-async fn create_index(archive: &mut Archive<std::fs::File>) -> Result<HashMap<PathBuf, IndexValue>> {
+/// How many leading bytes feed the cheap partial signature.
+const PARTIAL_BLOCK: u64 = 4096;
+
+/// Reads the header fields shared by every index entry, normalizing the
+/// stored cksum against `path` the way `Builder::append_data` recomputes it on
+/// write (see its own doc comment). An entry whose on-disk name isn't already
+/// normalized (e.g. GNU tar's `./` prefix) would otherwise carry a cksum that
+/// differs from the one an archive this tool writes would have, so it's
+/// recomputed here the same way the write paths will, making it safe to reuse
+/// an index entry's cksum anywhere a freshly-written archive's cksum is
+/// expected (e.g. the manifest).
+fn header_fields(header: &Header, path: &Path) -> Result<(u32, u64, u32, u64, u64, u64)> {
+    let mut renamed = header.clone();
+    renamed.set_path(path).with_context(|| "invalid path".to_string())?;
+    renamed.set_cksum();
+    let cksum = renamed.cksum().with_context(|| "invalid cksum".to_string())?;
+    let size = header.size().with_context(|| "invalid size".to_string())?;
+    let mode = header.mode().with_context(|| "invalid mode".to_string())?;
+    let mtime = header.mtime().with_context(|| "invalid mtime".to_string())?;
+    let uid = header.uid().with_context(|| "invalid uid".to_string())?;
+    let gid = header.gid().with_context(|| "invalid gid".to_string())?;
+    Ok((cksum, size, mode, mtime, uid, gid))
+}
+
+async fn create_index(archive: &mut Archive<Box<dyn Read>>) -> Result<HashMap<PathBuf, IndexValue>> {
     let mut index = HashMap::new();
     let entries = archive.entries()?;
     for entry in entries {
         match entry.with_context(|| "corrupt tar".to_string()) {
             Ok(mut entry) => {
-                let path = entry.path()?.to_path_buf();
-                let cksum = entry.header().cksum().with_context(|| "invalid cksum".to_string())?;
+                let path = normalize_path(&entry.path()?);
+                let (cksum, size, mode, mtime, uid, gid) = header_fields(entry.header(), &path)?;
 
-                // create a Sha1 object
+                // Only hash the first block to keep indexing cheap; the full
+                // SHA-1 is filled in lazily in `diff` when it is actually needed.
                 let mut hasher = Sha1::new();
-                copy(&mut entry, &mut hasher).with_context(|| "sha1 hashing failed".to_string())?;
-                let sha1 = hasher.finalize();
-                let value = IndexValue { cksum, sha1: sha1.into() };
+                copy(&mut (&mut entry).take(PARTIAL_BLOCK), &mut hasher).with_context(|| "partial hashing failed".to_string())?;
+                let partial = hasher.finalize();
+                let value = IndexValue { size, cksum, partial: Some(partial.into()), sha1: None, mode, mtime, uid, gid };
                 index.insert(path, value);
             },
             Err(err) => {
                 return Err(err);
             }
         }
-        // dbg!(&entry)
     }
-    dbg!(&index);
+    Ok(index)
+}
+
+/// Computes the full SHA-1 of every `wanted` path in a single sequential pass
+/// over the archive, so confirming unchanged files costs one extra streaming
+/// read of the archive rather than one rescan per file.
+async fn full_hashes(tar: &Path, gzip: bool, wanted: &HashSet<PathBuf>) -> Result<HashMap<PathBuf, [u8; 20]>> {
+    let mut hashes = HashMap::new();
+    if wanted.is_empty() {
+        return Ok(hashes);
+    }
+    let mut archive = open_tar(tar, gzip)?;
+    for result_entry in archive.entries()? {
+        let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
+        let path = normalize_path(&entry.path()?);
+        if wanted.contains(&path) {
+            let mut hasher = Sha1::new();
+            copy(&mut entry, &mut hasher).with_context(|| "sha1 hashing failed".to_string())?;
+            hashes.insert(path, hasher.finalize().into());
+        }
+    }
+    Ok(hashes)
+}
+
+/// Builds a complete index with the full SHA-1 of every entry, used as the
+/// post-apply manifest embedded in the delta and re-computed by `verify`.
+async fn create_manifest(archive: &mut Archive<Box<dyn Read>>) -> Result<HashMap<PathBuf, IndexValue>> {
+    let mut index = HashMap::new();
+    for result_entry in archive.entries()? {
+        let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
+        let path = normalize_path(&entry.path()?);
+        let (cksum, size, mode, mtime, uid, gid) = header_fields(entry.header(), &path)?;
+
+        let mut hasher = Sha1::new();
+        copy(&mut entry, &mut hasher).with_context(|| "sha1 hashing failed".to_string())?;
+        let sha1 = hasher.finalize();
+        index.insert(path, IndexValue { size, cksum, partial: None, sha1: Some(sha1.into()), mode, mtime, uid, gid });
+    }
     Ok(index)
 }
 
 fn entry_has_changed(a: &IndexValue, b: &IndexValue) -> bool {
-    a != b
+    // Content only: the header checksum is covered by `header_changed` since it
+    // reflects the POSIX attributes, not the file bytes.
+    a.size != b.size || a.partial != b.partial || a.sha1 != b.sha1
+}
+
+/// True when a POSIX header attribute (mode, mtime, uid, gid) differs. Content
+/// is not compared; `diff` checks `entry_has_changed` first.
+fn header_changed(a: &IndexValue, b: &IndexValue) -> bool {
+    a.mode != b.mode || a.mtime != b.mtime || a.uid != b.uid || a.gid != b.gid
 }
 
-async fn create_delta_archive(changed: HashSet<PathBuf>, new_tar: &Path,  metadata: &DiffMetadata, out: &Path) -> Result<()> {
+async fn create_delta_archive(changed: HashSet<PathBuf>, new_tar: &Path, gzip: bool, metadata: &DiffMetadata, out: &Path) -> Result<()> {
     // We now create a new tar file that consists of
     // a metadata file and the other files.
     // It will be structured like this:
@@ -108,25 +452,25 @@ async fn create_delta_archive(changed: HashSet<PathBuf>, new_tar: &Path,  metada
     // __delta_metadata.json: the json file
 
     // TODO: this can be done in parallel
-    let file = std::fs::File::create(out)?;
-    let mut builder = Builder::new(file);
+    let writer = create_writer(out, metadata.codec)?;
+    let mut builder = Builder::new(writer);
 
     // Write the metadata file to the tar archive AS FIRST FILE
     let metadata_path = PathBuf::from(DELTA_METADATA_FILE);
     let mut metadata_header = Header::new_old();
 
     let mut metadata_bytes: Vec<u8> = Vec::new();
-    serde_json::to_writer(&mut metadata_bytes, &metadata).unwrap();
+    serde_json::to_writer(&mut metadata_bytes, &metadata.to_versioned()).unwrap();
     metadata_header.set_size(metadata_bytes.len() as u64);
     builder.append_data(&mut metadata_header, metadata_path, &metadata_bytes[..]).with_context(|| "unable to add metadata".to_string())?;
 
-    let mut new_tar = open_tar(new_tar, false)?;
+    let mut new_tar = open_tar(new_tar, gzip)?;
 
-    let entries = new_tar.entries_with_seek()?;
+    let entries = new_tar.entries()?;
     for result_entry in entries {
         let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
 
-        let path = entry.path()?.to_path_buf();
+        let path = normalize_path(&entry.path()?);
         if changed.contains(&path) {
             let mut header = entry.header().clone();
             builder.append_data(&mut header, path, &mut entry).with_context(|| "unable to add file".to_string())?;
@@ -147,96 +491,427 @@ async fn diff(old: &Path, new: &Path, gzip: bool, out: &Path) -> Result<()> {
     let old_index = old_index?;
     let new_index = new_index?;
 
-    dbg!(&old_index);
-    dbg!(&new_index);
-
     // do the computation of the diff
     let mut changed = HashSet::new();
     let mut added = HashSet::new();
     let mut removed = HashSet::new();
+    let mut metadata_changed: Vec<(PathBuf, HeaderDelta)> = Vec::new();
 
-    // TODO: deal with removed files
+    // First split by the cheap pre-filter: a differing size or partial signature
+    // means a definite change, and the rest become candidates whose full SHA-1
+    // we still need to compare.
+    let mut candidates: HashSet<PathBuf> = HashSet::new();
     for (path, new_value) in new_index.iter() {
         if let Some(old_value) = old_index.get(path) {
-            if entry_has_changed(new_value, old_value) {
+            if new_value.size != old_value.size || new_value.partial != old_value.partial {
                 changed.insert(path.clone());
+            } else {
+                candidates.insert(path.clone());
             }
         } else {
             added.insert(path.clone());
         }
     }
 
+    // Compute the candidates' full hashes in ONE sequential pass per archive,
+    // rather than reopening and rescanning for every path.
+    let old_full = full_hashes(old, gzip, &candidates).await?;
+    let new_full = full_hashes(new, gzip, &candidates).await?;
+
+    for path in &candidates {
+        let mut old_value = old_index.get(path).unwrap().clone();
+        let mut new_value = new_index.get(path).unwrap().clone();
+        old_value.sha1 = old_full.get(path).copied();
+        new_value.sha1 = new_full.get(path).copied();
+        if entry_has_changed(&old_value, &new_value) {
+            changed.insert(path.clone());
+        } else if header_changed(&old_value, &new_value) {
+            // Content is identical but a POSIX attribute changed; record just
+            // the new attributes instead of the whole file.
+            metadata_changed.push((path.clone(), HeaderDelta::from_index(&new_value)));
+        }
+    }
+
     for path in old_index.keys() {
         if !new_index.contains_key(path) {
             removed.insert(path.clone());
         }
     }
 
-    let changed_vec = Vec::from_iter(changed.clone().into_iter());
-    let added_vec = Vec::from_iter(added.clone().into_iter());
+    let changed_vec = Vec::from_iter(changed.clone());
+    let added_vec = Vec::from_iter(added.clone());
+
+    // Every path in `new` needs a full SHA-1 for the manifest. The candidates
+    // pass above already hashed every candidate (`new_full`): each path present
+    // on both sides with equal size and partial signature. So only paths the cheap pre-filter already classified as changed or
+    // added (never candidates) still need one; hashing just those instead of
+    // re-hashing the whole archive again is what keeps the manifest from
+    // undoing the two-phase hashing this function just did.
+    let uncovered: HashSet<PathBuf> = changed_vec.iter().chain(added_vec.iter()).filter(|p| !candidates.contains(*p)).cloned().collect();
+    let uncovered_full = full_hashes(new, gzip, &uncovered).await?;
+
+    let manifest: HashMap<PathBuf, IndexValue> = new_index
+        .into_iter()
+        .map(|(path, mut value)| {
+            value.sha1 = new_full.get(&path).or_else(|| uncovered_full.get(&path)).copied();
+            (path, value)
+        })
+        .collect();
+    let manifest = Some(manifest);
 
     changed.extend(added);
 
+    // Pick the output codec from the delta path; -c/--gzip may force gzip, but
+    // only when the extension agrees so the delta stays readable.
+    let codec = resolve_output_codec(out, gzip)?;
+
     let metadata = DiffMetadata {
         changed: changed_vec,
         added: added_vec,
-        removed: Vec::from_iter(removed.into_iter()),
+        removed: Vec::from_iter(removed),
+        codec,
+        metadata_changed,
+        manifest,
     };
-    dbg!(&metadata);
 
-    dbg!(&changed);
-
-    create_delta_archive(changed, new, &metadata, out).await?;
+    create_delta_archive(changed, new, gzip, &metadata, out).await?;
 
     Ok(())
 }
 
-async fn apply_delta_archive(old: &Path, diff: &Path, out: &Path) -> Result<()> {
-    let file = std::fs::File::create(out)?;
-    let mut builder = Builder::new(file);
+/// Reads the tagged metadata entry (the first file) out of a delta archive and
+/// up-converts it to the current in-memory representation, so deltas written by
+/// older releases stay readable.
+fn load_metadata(diff: &Path) -> Result<DiffMetadata> {
+    let mut diff_tar = open_tar(diff, false)?;
+    let mut entries = diff_tar.entries()?;
+    let first = entries.next();
+    if first.is_none() {
+        bail!("empty delta file");
+    }
+    let mut entry = first.unwrap().with_context(|| "corrupt tar missing metadata".to_string())?;
+    let path = normalize_path(&entry.path()?);
+    if path != Path::new(DELTA_METADATA_FILE) {
+        bail!("delta file is missing metadata file as first entry");
+    }
+    let on_disk: MetadataOnDisk = serde_json::from_reader(&mut entry).with_context(|| "invalid metadata file".to_string())?;
+    Ok(on_disk.into_current())
+}
 
+/// Where a surviving file's content comes from when folding a delta chain.
+#[derive(Debug, Clone)]
+enum Source {
+    Base,
+    Delta(PathBuf),
+}
 
-    let mut diff_tar = open_tar(diff, false)?;
-    let mut entries = diff_tar.entries_with_seek()?;
+/// A surviving path's resolved state: which archive supplies its content, plus
+/// any metadata-only header change accumulated after that content was stored.
+#[derive(Debug, Clone)]
+struct ChainEntry {
+    source: Source,
+    header: Option<HeaderDelta>,
+}
+
+/// Applies a metadata-only header change onto a cloned entry header.
+fn apply_header_delta(header: &mut Header, delta: &HeaderDelta) {
+    header.set_mode(delta.mode);
+    header.set_mtime(delta.mtime);
+    header.set_uid(delta.uid);
+    header.set_gid(delta.gid);
+}
+
+/// Folds an ordered chain of deltas over a base archive and returns the final
+/// `path -> entry` map: for every surviving file, which archive last supplied
+/// its content and any later metadata-only header change. A file removed in one
+/// delta and re-added in a later one ends up present, sourced from the later
+/// delta.
+fn resolve_chain(base: &Path, deltas: &[PathBuf], gzip: bool) -> Result<HashMap<PathBuf, ChainEntry>> {
+    // Start from the full membership of the base archive.
+    let mut sources: HashMap<PathBuf, ChainEntry> = HashMap::new();
+    let mut base_tar = open_tar(base, gzip)?;
+    for result_entry in base_tar.entries()? {
+        let entry = result_entry.with_context(|| "corrupt tar".to_string())?;
+        let path = normalize_path(&entry.path()?);
+        sources.insert(path, ChainEntry { source: Source::Base, header: None });
+    }
+
+    for delta in deltas {
+        let metadata = load_metadata(delta)?;
+
+        // Guard against a delta whose old-side assumptions don't hold: changed,
+        // removed and metadata-changed files must already be present, and added
+        // files must not.
+        for path in &metadata.changed {
+            if !sources.contains_key(path) {
+                bail!("delta {:?} changes {:?} which is not present in the accumulated state", delta, path);
+            }
+        }
+        for path in &metadata.removed {
+            if !sources.contains_key(path) {
+                bail!("delta {:?} removes {:?} which is not present in the accumulated state", delta, path);
+            }
+        }
+        for (path, _) in &metadata.metadata_changed {
+            if !sources.contains_key(path) {
+                bail!("delta {:?} changes metadata of {:?} which is not present in the accumulated state", delta, path);
+            }
+        }
+        for path in &metadata.added {
+            if sources.contains_key(path) {
+                bail!("delta {:?} adds {:?} which already exists in the accumulated state", delta, path);
+            }
+        }
+
+        for path in &metadata.removed {
+            sources.remove(path);
+        }
+        for path in metadata.changed.iter().chain(metadata.added.iter()) {
+            // Fresh content re-stores the whole entry, so drop any prior header
+            // override.
+            sources.insert(path.clone(), ChainEntry { source: Source::Delta(delta.clone()), header: None });
+        }
+        for (path, hdr) in &metadata.metadata_changed {
+            // Content is unchanged; only the header attributes move forward.
+            if let Some(entry) = sources.get_mut(path) {
+                entry.header = Some(hdr.clone());
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+async fn apply_chain(base: &Path, deltas: &[PathBuf], gzip: bool, out: &Path) -> Result<()> {
+    let sources = resolve_chain(base, deltas, gzip)?;
 
-    // read the metadata entry (should be first entry)
-    let metadata: DiffMetadata = {
-        let first = entries.next();
-        if first.is_none() {
-            bail!("empty delta file");
+    let codec = resolve_output_codec(out, gzip)?;
+    let writer = create_writer(out, codec)?;
+    let mut builder = Builder::new(writer);
+
+    // Stream the survivors from the base first, then from each delta in order,
+    // so every path is copied exactly once from whichever archive last
+    // supplied it.
+    let mut base_tar = open_tar(base, gzip)?;
+    for result_entry in base_tar.entries()? {
+        let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
+        let path = normalize_path(&entry.path()?);
+        if let Some(chain_entry @ ChainEntry { source: Source::Base, .. }) = sources.get(&path) {
+            let mut header = entry.header().clone();
+            if let Some(hdr) = &chain_entry.header {
+                apply_header_delta(&mut header, hdr);
+            }
+            builder.append_data(&mut header, path, &mut entry).with_context(|| "unable to add base entry".to_string())?;
+        }
+    }
+
+    for delta in deltas {
+        let mut delta_tar = open_tar(delta, false)?;
+        for result_entry in delta_tar.entries()? {
+            let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
+            let path = normalize_path(&entry.path()?);
+            if path == Path::new(DELTA_METADATA_FILE) {
+                continue;
+            }
+            if let Some(chain_entry @ ChainEntry { source: Source::Delta(d), .. }) = sources.get(&path) {
+                if d != delta {
+                    continue;
+                }
+                let mut header = entry.header().clone();
+                if let Some(hdr) = &chain_entry.header {
+                    apply_header_delta(&mut header, hdr);
+                }
+                builder.append_data(&mut header, path, &mut entry).with_context(|| "unable to add delta entry".to_string())?;
+            }
         }
-        let mut entry = first.unwrap().with_context(|| "corrupt tar missing metadata".to_string())?;
-        let path = entry.path()?.to_path_buf();
-        if path != PathBuf::from(DELTA_METADATA_FILE) {
-            bail!("delta file is missing metadata file as first entry");
+    }
+
+    builder.finish().with_context(|| "failed to apply delta chain".to_string())?;
+
+    Ok(())
+}
+
+/// The resolved final representation of a path while compacting a delta chain.
+struct FinalEntry {
+    /// Delta supplying the last-winning content, or `None` when the content
+    /// stays in the common base (a metadata-only change).
+    content: Option<PathBuf>,
+    header: Option<HeaderDelta>,
+    added_by_chain: bool,
+}
+
+async fn compact(deltas: &[PathBuf], gzip: bool, out: &Path) -> Result<()> {
+    // Resolve the net effect of applying the whole chain in order, tracking for
+    // each surviving path where its last-winning content lives and whether the
+    // chain introduced it (vs. it pre-existing in the common base).
+    let mut present: HashMap<PathBuf, FinalEntry> = HashMap::new();
+    let mut removed: HashSet<PathBuf> = HashSet::new();
+    // Applying the whole chain yields the last delta's new state, so its target
+    // manifest is also the consolidated delta's manifest.
+    let mut manifest: Option<HashMap<PathBuf, IndexValue>> = None;
+
+    for delta in deltas {
+        let metadata = load_metadata(delta)?;
+        for path in &metadata.added {
+            // A path removed earlier in the chain and now re-added may still
+            // exist in the common base, so classify it as `changed` (safe
+            // whether or not the base held it) rather than `added`.
+            let re_added = removed.remove(path);
+            present.insert(path.clone(), FinalEntry { content: Some(delta.clone()), header: None, added_by_chain: !re_added });
+        }
+        for path in &metadata.changed {
+            removed.remove(path);
+            let added_by_chain = present.get(path).map(|e| e.added_by_chain).unwrap_or(false);
+            present.insert(path.clone(), FinalEntry { content: Some(delta.clone()), header: None, added_by_chain });
+        }
+        for (path, hdr) in &metadata.metadata_changed {
+            removed.remove(path);
+            let mut e = present.remove(path).unwrap_or(FinalEntry { content: None, header: None, added_by_chain: false });
+            e.header = Some(hdr.clone());
+            present.insert(path.clone(), e);
+        }
+        for path in &metadata.removed {
+            match present.remove(path) {
+                // Added earlier in the chain then removed: it vanishes entirely.
+                Some(e) if e.added_by_chain => {}
+                // Otherwise it was a base file, so this is a net removal.
+                _ => { removed.insert(path.clone()); }
+            }
         }
-        serde_json::from_reader(&mut entry).with_context(|| "invalid metadata file".to_string())?
+        manifest = metadata.manifest;
+    }
+
+    // Classify the survivors into the merged delta's sets.
+    let mut added_vec = Vec::new();
+    let mut changed_vec = Vec::new();
+    let mut metadata_changed = Vec::new();
+    let mut content_source: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut content_header: HashMap<PathBuf, HeaderDelta> = HashMap::new();
+    for (path, e) in &present {
+        match &e.content {
+            Some(src) => {
+                content_source.insert(path.clone(), src.clone());
+                // A later metadata-only change on a content-bearing file is
+                // replayed onto its emitted header rather than dropped.
+                if let Some(hdr) = &e.header {
+                    content_header.insert(path.clone(), hdr.clone());
+                }
+                if e.added_by_chain {
+                    added_vec.push(path.clone());
+                } else {
+                    changed_vec.push(path.clone());
+                }
+            }
+            None => {
+                if let Some(hdr) = &e.header {
+                    metadata_changed.push((path.clone(), hdr.clone()));
+                }
+            }
+        }
+    }
+
+    // Pick the output codec the same way `Diff`/`ApplyChain` do, so
+    // `-c/--gzip` behaves consistently across every subcommand that writes an
+    // archive.
+    let codec = resolve_output_codec(out, gzip)?;
+    let metadata = DiffMetadata {
+        changed: changed_vec,
+        added: added_vec,
+        removed: Vec::from_iter(removed),
+        codec,
+        metadata_changed,
+        manifest,
     };
 
+    // Write the consolidated delta: metadata first, then the last-winning
+    // content of each surviving file, pulled from whichever delta supplied it,
+    // so no superseded file data survives.
+    let writer = create_writer(out, metadata.codec)?;
+    let mut builder = Builder::new(writer);
+
+    let metadata_path = PathBuf::from(DELTA_METADATA_FILE);
+    let mut metadata_header = Header::new_old();
+    let mut metadata_bytes: Vec<u8> = Vec::new();
+    serde_json::to_writer(&mut metadata_bytes, &metadata.to_versioned()).unwrap();
+    metadata_header.set_size(metadata_bytes.len() as u64);
+    builder.append_data(&mut metadata_header, metadata_path, &metadata_bytes[..]).with_context(|| "unable to add metadata".to_string())?;
+
+    for delta in deltas {
+        let mut delta_tar = open_tar(delta, false)?;
+        for result_entry in delta_tar.entries()? {
+            let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
+            let path = normalize_path(&entry.path()?);
+            if path == Path::new(DELTA_METADATA_FILE) {
+                continue;
+            }
+            if matches!(content_source.get(&path), Some(src) if src == delta) {
+                let mut header = entry.header().clone();
+                if let Some(hdr) = content_header.get(&path) {
+                    apply_header_delta(&mut header, hdr);
+                }
+                builder.append_data(&mut header, path, &mut entry).with_context(|| "unable to add file".to_string())?;
+            }
+        }
+    }
+
+    builder.finish().with_context(|| "failed to create compacted delta".to_string())?;
+
+    Ok(())
+}
+
+async fn apply_delta_archive(old: &Path, diff: &Path, out: &Path) -> Result<()> {
+    let metadata = load_metadata(diff)?;
+
+    // The reconstructed archive is written with the codec recorded in the
+    // delta. `out`'s extension has no say in which codec is used, but it must
+    // still agree with it: every reader (including this tool's own `verify`)
+    // re-detects the codec from the filename alone, so a mismatch here would
+    // silently produce an archive nothing else can open.
+    if Codec::from_path(out) != metadata.codec {
+        bail!(
+            "delta {:?} was recorded with {:?} compression but {} does not have a matching extension; rename the output to match",
+            diff,
+            metadata.codec,
+            out.display()
+        );
+    }
+    let writer = create_writer(out, metadata.codec)?;
+    let mut builder = Builder::new(writer);
+
     let changed: HashSet<&PathBuf> = HashSet::from_iter(metadata.changed.iter());
     let added: HashSet<&PathBuf> = HashSet::from_iter(metadata.added.iter());
     let removed: HashSet<&PathBuf> = HashSet::from_iter(metadata.removed.iter());
+    let meta_changes: HashMap<&PathBuf, &HeaderDelta> = metadata.metadata_changed.iter().map(|(p, d)| (p, d)).collect();
 
     // add old entries
     println!("Adding old entries..");
     let mut old_tar = open_tar(old, false)?;
-    let old_entries = old_tar.entries_with_seek()?;
+    let old_entries = old_tar.entries()?;
     for result_entry in old_entries {
         let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
 
-        let path = entry.path()?.to_path_buf();
+        let path = normalize_path(&entry.path()?);
         if !removed.contains(&path) && !changed.contains(&path) {
             let mut header = entry.header().clone();
+            if let Some(delta) = meta_changes.get(&path) {
+                // Replay a metadata-only change onto the old entry's header; the
+                // file content is re-used unchanged from the old archive.
+                apply_header_delta(&mut header, delta);
+            }
             builder.append_data(&mut header, path, &mut entry).with_context(|| "unable to add old entry".to_string())?;
         }
     }
 
     // apply diff
     println!("Applying diff..");
-    for result_entry in entries {
+    let mut diff_tar = open_tar(diff, false)?;
+    for result_entry in diff_tar.entries()? {
         let mut entry = result_entry.with_context(|| "corrupt tar".to_string())?;
 
-        let path = entry.path()?.to_path_buf();
+        let path = normalize_path(&entry.path()?);
+        if path == Path::new(DELTA_METADATA_FILE) {
+            continue;
+        }
         if changed.contains(&path) || added.contains(&path) {
             let mut header = entry.header().clone();
             builder.append_data(&mut header, path, &mut entry).with_context(|| "unable to add diff change".to_string())?;
@@ -245,8 +920,6 @@ async fn apply_delta_archive(old: &Path, diff: &Path, out: &Path) -> Result<()>
 
     builder.finish().with_context(|| "failed to apply delta archive".to_string())?;
 
-    dbg!(&metadata);
-
     Ok(())
 }
 
@@ -256,14 +929,63 @@ async fn apply(old: &Path, diff: &Path, out: &Path) -> Result<()> {
     Ok(())
 }
 
+async fn verify(archive: &Path, diff: &Path) -> Result<()> {
+    let metadata = load_metadata(diff)?;
+
+    // A missing manifest means the delta predates the manifest field (a
+    // legacy or V1-V3 delta, or one compacted from a chain ending in one) and
+    // there's genuinely nothing to check against.
+    // That's distinct from a present-but-empty manifest, which means the
+    // target archive legitimately has zero files.
+    let Some(manifest) = &metadata.manifest else {
+        bail!("delta {:?} predates the hash manifest; nothing to verify against", diff);
+    };
+
+    let mut archive_tar = open_tar(archive, false)?;
+    let actual = create_manifest(&mut archive_tar).await?;
+
+    // Compare every expected path against the re-indexed archive, reporting
+    // missing, extra and mismatched files.
+    let mut ok = true;
+    for (path, expected) in manifest {
+        match actual.get(path) {
+            None => {
+                println!("missing: {:?}", path);
+                ok = false;
+            }
+            Some(got) => {
+                if got.sha1 != expected.sha1 || got.cksum != expected.cksum {
+                    println!("mismatch: {:?}", path);
+                    ok = false;
+                }
+            }
+        }
+    }
+    for path in actual.keys() {
+        if !manifest.contains_key(path) {
+            println!("extra: {:?}", path);
+            ok = false;
+        }
+    }
+
+    if !ok {
+        bail!("verification failed: {:?} does not match the manifest in {:?}", archive, diff);
+    }
+    println!("ok: {} files verified", manifest.len());
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
-    dbg!(&args);
 
     match &args.command {
-        Commands::Diff { old, new, out } => diff(old, new, args.gzip, &out).await?,
-        Commands::Apply { old, diff, out } => apply(old, diff, &out).await?,
+        Commands::Diff { old, new, out } => diff(old, new, args.gzip, out).await?,
+        Commands::Apply { old, diff, out } => apply(old, diff, out).await?,
+        Commands::ApplyChain { base, deltas, out } => apply_chain(base, deltas, args.gzip, out).await?,
+        Commands::Compact { deltas, out } => compact(deltas, args.gzip, out).await?,
+        Commands::Verify { archive, diff } => verify(archive, diff).await?,
     }
     Ok(())
 }
@@ -0,0 +1,50 @@
+//! Round-trips `diff`/`apply`/`verify` over a gzip-compressed delta and
+//! reconstructed archive, exercising the codec plumbing end to end: the delta
+//! is written as `.tar.gz`, `apply` must read it back and write a `.tar.gz`
+//! output of its own, and `verify` must be able to open both.
+
+mod common;
+
+use common::{bin, scratch_dir, write_tar_gz};
+
+#[test]
+fn diff_apply_verify_round_trip_through_gzip() {
+    let dir = scratch_dir("codec-gzip-roundtrip");
+    let old = dir.join("old.tar.gz");
+    let new = dir.join("new.tar.gz");
+    let delta = dir.join("delta.tar.gz");
+    let out = dir.join("out.tar.gz");
+
+    write_tar_gz(&old, &[("a.txt", b"hello"), ("b.txt", b"unchanged")]);
+    write_tar_gz(&new, &[("a.txt", b"hello, world"), ("b.txt", b"unchanged")]);
+
+    let status = bin().args(["diff", old.to_str().unwrap(), new.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap();
+    assert!(status.success(), "diff should produce a gzip delta from gzip inputs");
+
+    let status = bin().args(["apply", old.to_str().unwrap(), delta.to_str().unwrap(), out.to_str().unwrap()]).status().unwrap();
+    assert!(status.success(), "apply should write a gzip output to match the delta's recorded codec");
+
+    let status = bin().args(["verify", out.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap();
+    assert!(status.success(), "verify should be able to open the gzip result and the gzip delta");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn apply_rejects_an_output_extension_that_disagrees_with_the_delta_codec() {
+    let dir = scratch_dir("codec-mismatch");
+    let old = dir.join("old.tar.gz");
+    let new = dir.join("new.tar.gz");
+    let delta = dir.join("delta.tar.gz");
+    let out = dir.join("out.tar");
+
+    write_tar_gz(&old, &[("a.txt", b"hello")]);
+    write_tar_gz(&new, &[("a.txt", b"hello, world")]);
+
+    assert!(bin().args(["diff", old.to_str().unwrap(), new.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap().success());
+
+    let status = bin().args(["apply", old.to_str().unwrap(), delta.to_str().unwrap(), out.to_str().unwrap()]).status().unwrap();
+    assert!(!status.success(), "apply should refuse to write a gzip-coded delta to a plain .tar name");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,89 @@
+//! Shared helpers for the integration tests, which each spawn the compiled
+//! binary (this is a bin-only crate, so internal functions aren't importable
+//! from `tests/`).
+
+#![allow(dead_code)]
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tar::{Builder, Header};
+
+pub fn bin() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_tarpatch"))
+}
+
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tarpatch-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn write_tar(path: &Path, entries: &[(&str, &[u8])]) {
+    let mut builder = Builder::new(File::create(path).unwrap());
+    write_entries(&mut builder, entries);
+    builder.finish().unwrap();
+}
+
+/// Same as [`write_tar`] but gzip-compresses the stream, for tests exercising
+/// a non-plain codec.
+pub fn write_tar_gz(path: &Path, entries: &[(&str, &[u8])]) {
+    let encoder = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+    let mut builder = Builder::new(encoder);
+    write_entries(&mut builder, entries);
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+fn write_entries<W: std::io::Write>(builder: &mut Builder<W>, entries: &[(&str, &[u8])]) {
+    for (name, content) in entries {
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        header.set_cksum();
+        builder.append_data(&mut header, *name, *content).unwrap();
+    }
+}
+
+/// Writes a tar archive at `path` containing `entries`, with every name
+/// prefixed with `./` the way `tar -C dir .` stores them. Writes the name
+/// bytes directly rather than going through `Header::set_path`, since that
+/// helper strips a leading `./` itself — real GNU tar does not, and keeps it
+/// literally in the stored name (and thus in the header checksum).
+pub fn write_dotslash_tar(path: &Path, entries: &[(&str, &[u8])]) {
+    let mut builder = Builder::new(File::create(path).unwrap());
+    for (name, content) in entries {
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        let dotslash_name = format!("./{name}");
+        header.as_old_mut().name[..dotslash_name.len()].copy_from_slice(dotslash_name.as_bytes());
+        header.set_cksum();
+        builder.append(&header, *content).unwrap();
+    }
+    builder.finish().unwrap();
+}
+
+pub fn read_entries(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut archive = tar::Archive::new(File::open(path).unwrap());
+    archive
+        .entries()
+        .unwrap()
+        .map(|e| {
+            let mut entry = e.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = Vec::new();
+            std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+            (name, content)
+        })
+        .collect()
+}
@@ -0,0 +1,48 @@
+//! Exercises `compact`'s own fold over a delta chain (it does not go through
+//! `resolve_chain`) for the non-obvious case the
+//! request called out explicitly: a path added by one delta and removed by a
+//! later one in the same chain must vanish entirely from the merged delta,
+//! not appear as a no-op add/remove pair.
+
+mod common;
+
+use common::{bin, read_entries, scratch_dir, write_tar};
+
+#[test]
+fn compact_drops_a_path_added_then_removed_within_the_chain() {
+    let dir = scratch_dir("compact-add-remove");
+
+    let base = dir.join("base.tar");
+    let state1 = dir.join("state1.tar");
+    let state2 = dir.join("state2.tar");
+    let delta1 = dir.join("delta1.tar");
+    let delta2 = dir.join("delta2.tar");
+    let compacted = dir.join("compacted.tar");
+    let out = dir.join("out.tar");
+
+    // base only has b.txt; state1 adds a.txt (delta1); state2 removes it
+    // again (delta2), so across the chain a.txt never existed.
+    write_tar(&base, &[("b.txt", b"unchanged")]);
+    write_tar(&state1, &[("a.txt", b"transient"), ("b.txt", b"unchanged")]);
+    write_tar(&state2, &[("b.txt", b"unchanged")]);
+
+    assert!(bin().args(["diff", base.to_str().unwrap(), state1.to_str().unwrap(), delta1.to_str().unwrap()]).status().unwrap().success());
+    assert!(bin().args(["diff", state1.to_str().unwrap(), state2.to_str().unwrap(), delta2.to_str().unwrap()]).status().unwrap().success());
+
+    let status = bin()
+        .args(["compact", "--out", compacted.to_str().unwrap(), delta1.to_str().unwrap(), delta2.to_str().unwrap()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let status = bin()
+        .args(["apply-chain", base.to_str().unwrap(), "--out", out.to_str().unwrap(), compacted.to_str().unwrap()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let entries = read_entries(&out);
+    assert_eq!(entries, vec![("b.txt".to_string(), b"unchanged".to_vec())], "a.txt was added then removed within the chain and must not survive compaction");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
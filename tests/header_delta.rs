@@ -0,0 +1,57 @@
+//! Covers the metadata-only path: a file whose content is byte-identical but
+//! whose POSIX mode changed must be recorded as a `HeaderDelta` and replayed
+//! onto the old entry by `apply`, rather than being treated as a content
+//! change.
+
+mod common;
+
+use std::fs::File;
+
+use tar::{Builder, Header};
+
+use common::{bin, scratch_dir};
+
+fn write_tar_with_mode(path: &std::path::Path, entries: &[(&str, &[u8], u32)]) {
+    let mut builder = Builder::new(File::create(path).unwrap());
+    for (name, content, mode) in entries {
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(*mode);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        header.set_cksum();
+        builder.append_data(&mut header, *name, *content).unwrap();
+    }
+    builder.finish().unwrap();
+}
+
+#[test]
+fn apply_replays_a_mode_only_change_without_touching_content() {
+    let dir = scratch_dir("header-delta-mode");
+    let old = dir.join("old.tar");
+    let new = dir.join("new.tar");
+    let delta = dir.join("delta.tar");
+    let out = dir.join("out.tar");
+
+    write_tar_with_mode(&old, &[("script.sh", b"echo hi", 0o644)]);
+    write_tar_with_mode(&new, &[("script.sh", b"echo hi", 0o755)]);
+
+    assert!(bin().args(["diff", old.to_str().unwrap(), new.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap().success());
+    assert!(bin().args(["apply", old.to_str().unwrap(), delta.to_str().unwrap(), out.to_str().unwrap()]).status().unwrap().success());
+
+    let mut archive = tar::Archive::new(File::open(&out).unwrap());
+    let mut entries = archive.entries().unwrap();
+    let mut entry = entries.next().unwrap().unwrap();
+    assert_eq!(entry.path().unwrap().to_str().unwrap(), "script.sh");
+    assert_eq!(entry.header().mode().unwrap(), 0o755, "the new mode must be replayed onto the old entry");
+
+    let mut content = Vec::new();
+    std::io::Read::read_to_end(&mut entry, &mut content).unwrap();
+    assert_eq!(content, b"echo hi", "content must be reused unchanged since only the header differed");
+
+    let status = bin().args(["verify", out.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap();
+    assert!(status.success());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
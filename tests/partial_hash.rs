@@ -0,0 +1,50 @@
+//! Exercises the two-phase hash comparison: a file whose first `PARTIAL_BLOCK`
+//! bytes match but whose tail differs must still be detected as changed, and
+//! a file that's genuinely byte-identical past that same size must be left
+//! out of the delta.
+
+mod common;
+
+use common::{bin, read_entries, scratch_dir, write_tar};
+
+#[test]
+fn diff_disambiguates_past_the_partial_block_via_full_hash() {
+    let dir = scratch_dir("partial-hash");
+    let old = dir.join("old.tar");
+    let new = dir.join("new.tar");
+    let delta = dir.join("delta.tar");
+    let out = dir.join("out.tar");
+
+    // Both files are larger than PARTIAL_BLOCK (4096) and share identical
+    // first bytes, so the cheap signature alone can't tell them apart; only a
+    // full-content comparison can.
+    let head = vec![b'a'; 4096];
+
+    let mut unchanged = head.clone();
+    unchanged.extend_from_slice(b"same tail");
+
+    let mut tail_changed_old = head.clone();
+    tail_changed_old.extend_from_slice(b"old tail");
+    let mut tail_changed_new = head;
+    tail_changed_new.extend_from_slice(b"new tail");
+
+    write_tar(&old, &[("same.bin", &unchanged), ("tail-changed.bin", &tail_changed_old)]);
+    write_tar(&new, &[("same.bin", &unchanged), ("tail-changed.bin", &tail_changed_new)]);
+
+    assert!(bin().args(["diff", old.to_str().unwrap(), new.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap().success());
+    assert!(bin().args(["apply", old.to_str().unwrap(), delta.to_str().unwrap(), out.to_str().unwrap()]).status().unwrap().success());
+
+    let mut entries = read_entries(&out);
+    entries.sort();
+    let mut expected = vec![
+        ("same.bin".to_string(), unchanged),
+        ("tail-changed.bin".to_string(), tail_changed_new),
+    ];
+    expected.sort();
+    assert_eq!(entries, expected, "apply must reproduce the new tail even though the partial signature matched");
+
+    let status = bin().args(["verify", out.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap();
+    assert!(status.success());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
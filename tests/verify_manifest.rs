@@ -0,0 +1,63 @@
+//! Covers the distinction `verify` must draw between a delta that legitimately
+//! records zero files (a V4 delta whose target archive is empty) and one that
+//! never recorded a manifest at all (a pre-manifest delta) — only the latter
+//! is an error.
+
+mod common;
+
+use std::fs::File;
+
+use tar::{Builder, Header};
+
+use common::{bin, scratch_dir, write_tar};
+
+#[test]
+fn verify_reports_zero_files_for_a_genuinely_empty_target() {
+    let dir = scratch_dir("verify-empty-target");
+    let old = dir.join("old.tar");
+    let new = dir.join("new.tar");
+    let delta = dir.join("delta.tar");
+    let out = dir.join("out.tar");
+
+    write_tar(&old, &[("a.txt", b"gone soon")]);
+    write_tar(&new, &[]);
+
+    assert!(bin().args(["diff", old.to_str().unwrap(), new.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap().success());
+    assert!(bin().args(["apply", old.to_str().unwrap(), delta.to_str().unwrap(), out.to_str().unwrap()]).status().unwrap().success());
+
+    let status = bin().args(["verify", out.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap();
+    assert!(status.success(), "an archive that legitimately has zero files must verify as ok, not error out");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn verify_rejects_a_delta_that_predates_the_manifest_field() {
+    let dir = scratch_dir("verify-no-manifest");
+    let base = dir.join("base.tar");
+    let delta = dir.join("delta.tar");
+    let out = dir.join("out.tar");
+
+    write_tar(&base, &[("a.txt", b"hello")]);
+
+    // A bare, untagged metadata entry, same as the pre-versioning format,
+    // which never carries a manifest at all.
+    let metadata_json = r#"{"changed":[],"added":[],"removed":[]}"#;
+    let mut builder = Builder::new(File::create(&delta).unwrap());
+    let mut header = Header::new_old();
+    header.set_size(metadata_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header.set_cksum();
+    builder.append_data(&mut header, "__delta_metadata.json", metadata_json.as_bytes()).unwrap();
+    builder.finish().unwrap();
+
+    assert!(bin().args(["apply", base.to_str().unwrap(), delta.to_str().unwrap(), out.to_str().unwrap()]).status().unwrap().success());
+
+    let status = bin().args(["verify", out.to_str().unwrap(), delta.to_str().unwrap()]).status().unwrap();
+    assert!(!status.success(), "a delta with no manifest at all has nothing to verify against and must error");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
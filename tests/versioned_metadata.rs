@@ -0,0 +1,71 @@
+//! Covers `MetadataOnDisk`'s up-conversion of a pre-versioning delta: a
+//! metadata entry with no `version` tag at all (the shape written before the
+//! versioned envelope existed) must still `apply` correctly.
+
+mod common;
+
+use std::fs::File;
+
+use tar::{Builder, Header};
+
+use common::{bin, read_entries, scratch_dir, write_tar};
+
+/// Writes a delta archive whose metadata entry is the bare, untagged
+/// `{changed, added, removed}` shape from before `VersionedMetadata` existed,
+/// so loading it must go through `MetadataOnDisk::Legacy`, not a versioned
+/// variant.
+fn write_legacy_delta(path: &std::path::Path, added: &[&str], content: &[(&str, &[u8])]) {
+    let metadata_json = format!(
+        r#"{{"changed":[],"added":{},"removed":[]}}"#,
+        serde_json::to_string(added).unwrap()
+    );
+
+    let mut builder = Builder::new(File::create(path).unwrap());
+
+    let mut header = Header::new_old();
+    header.set_size(metadata_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header.set_cksum();
+    builder.append_data(&mut header, "__delta_metadata.json", metadata_json.as_bytes()).unwrap();
+
+    for (name, data) in content {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+        header.set_cksum();
+        builder.append_data(&mut header, *name, *data).unwrap();
+    }
+
+    builder.finish().unwrap();
+}
+
+#[test]
+fn apply_up_converts_a_pre_versioning_legacy_delta() {
+    let dir = scratch_dir("versioned-metadata-legacy");
+    let base = dir.join("base.tar");
+    let delta = dir.join("delta.tar");
+    let out = dir.join("out.tar");
+
+    write_tar(&base, &[("b.txt", b"unchanged")]);
+    write_legacy_delta(&delta, &["a.txt"], &[("a.txt", b"added by a legacy delta")]);
+
+    let status = bin().args(["apply", base.to_str().unwrap(), delta.to_str().unwrap(), out.to_str().unwrap()]).status().unwrap();
+    assert!(status.success(), "apply must up-convert a tagless legacy metadata entry rather than rejecting it");
+
+    let mut entries = read_entries(&out);
+    entries.sort();
+    let mut expected = vec![
+        ("a.txt".to_string(), b"added by a legacy delta".to_vec()),
+        ("b.txt".to_string(), b"unchanged".to_vec()),
+    ];
+    expected.sort();
+    assert_eq!(entries, expected);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}